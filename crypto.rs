@@ -0,0 +1,358 @@
+//! Hand-rolled ChaCha20-Poly1305 AEAD (RFC 8439) for the relay wire protocol.
+//!
+//! We avoid pulling a crate in so the tool stays a single self-contained
+//! binary, the same way the rest of the protocol is hand-coded. The public
+//! surface is two functions, [`seal`] and [`open`], plus [`random_nonce`]
+//! and [`parse_key`].
+
+/// ChaCha20 quarter-round on four words of the state.
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// Produce one 64-byte ChaCha20 keystream block for the given block counter.
+fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&CONSTANTS);
+    for i in 0..8 {
+        state[4 + i] = u32::from_le_bytes([
+            key[4 * i],
+            key[4 * i + 1],
+            key[4 * i + 2],
+            key[4 * i + 3],
+        ]);
+    }
+    state[12] = counter;
+    for i in 0..3 {
+        state[13 + i] = u32::from_le_bytes([
+            nonce[4 * i],
+            nonce[4 * i + 1],
+            nonce[4 * i + 2],
+            nonce[4 * i + 3],
+        ]);
+    }
+
+    let mut working = state;
+    for _ in 0..10 {
+        // Column rounds.
+        quarter_round(&mut working, 0, 4, 8, 12);
+        quarter_round(&mut working, 1, 5, 9, 13);
+        quarter_round(&mut working, 2, 6, 10, 14);
+        quarter_round(&mut working, 3, 7, 11, 15);
+        // Diagonal rounds.
+        quarter_round(&mut working, 0, 5, 10, 15);
+        quarter_round(&mut working, 1, 6, 11, 12);
+        quarter_round(&mut working, 2, 7, 8, 13);
+        quarter_round(&mut working, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; 64];
+    for i in 0..16 {
+        let word = working[i].wrapping_add(state[i]);
+        out[4 * i..4 * i + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+/// Encrypt/decrypt `data` in place with the ChaCha20 keystream, starting at
+/// block `counter`.
+fn chacha20_xor(key: &[u8; 32], counter: u32, nonce: &[u8; 12], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, counter + i as u32, nonce);
+        for (b, k) in chunk.iter_mut().zip(block.iter()) {
+            *b ^= k;
+        }
+    }
+}
+
+/// Poly1305 one-time MAC over `msg` keyed by `key` (first 16 bytes = r,
+/// second 16 = s), computed in 130-bit arithmetic over five 26-bit limbs.
+fn poly1305_mac(key: &[u8; 32], msg: &[u8]) -> [u8; 16] {
+    let mut r = [0u32; 5];
+    {
+        let t0 = u32::from_le_bytes([key[0], key[1], key[2], key[3]]);
+        let t1 = u32::from_le_bytes([key[4], key[5], key[6], key[7]]);
+        let t2 = u32::from_le_bytes([key[8], key[9], key[10], key[11]]);
+        let t3 = u32::from_le_bytes([key[12], key[13], key[14], key[15]]);
+        r[0] = t0 & 0x3ff_ffff;
+        r[1] = ((t0 >> 26) | (t1 << 6)) & 0x3ff_ff03;
+        r[2] = ((t1 >> 20) | (t2 << 12)) & 0x3ff_c0ff;
+        r[3] = ((t2 >> 14) | (t3 << 18)) & 0x3f0_3fff;
+        r[4] = (t3 >> 8) & 0x00f_ffff;
+    }
+
+    let mut h = [0u32; 5];
+    let chunks = msg.chunks(16);
+    // Process every full/partial 16-byte block with the 2^128 (or high-bit) pad.
+    for chunk in chunks {
+        let mut block = [0u8; 17];
+        block[..chunk.len()].copy_from_slice(chunk);
+        block[chunk.len()] = 1;
+
+        let t0 = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        let t1 = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+        let t2 = u32::from_le_bytes([block[8], block[9], block[10], block[11]]);
+        let t3 = u32::from_le_bytes([block[12], block[13], block[14], block[15]]);
+        h[0] += t0 & 0x3ff_ffff;
+        h[1] += ((t0 >> 26) | (t1 << 6)) & 0x3ff_ffff;
+        h[2] += ((t1 >> 20) | (t2 << 12)) & 0x3ff_ffff;
+        h[3] += ((t2 >> 14) | (t3 << 18)) & 0x3ff_ffff;
+        h[4] += (t3 >> 8) | ((block[16] as u32) << 24);
+
+        // h *= r  (mod 2^130 - 5)
+        let d0 = mul(h, r, 0);
+        let d1 = mul(h, r, 1);
+        let d2 = mul(h, r, 2);
+        let d3 = mul(h, r, 3);
+        let d4 = mul(h, r, 4);
+
+        let mut c = (d0 >> 26) as u32;
+        h[0] = d0 as u32 & 0x3ff_ffff;
+        let d1 = d1 + c as u64;
+        c = (d1 >> 26) as u32;
+        h[1] = d1 as u32 & 0x3ff_ffff;
+        let d2 = d2 + c as u64;
+        c = (d2 >> 26) as u32;
+        h[2] = d2 as u32 & 0x3ff_ffff;
+        let d3 = d3 + c as u64;
+        c = (d3 >> 26) as u32;
+        h[3] = d3 as u32 & 0x3ff_ffff;
+        let d4 = d4 + c as u64;
+        c = (d4 >> 26) as u32;
+        h[4] = d4 as u32 & 0x3ff_ffff;
+        h[0] += c * 5;
+        c = h[0] >> 26;
+        h[0] &= 0x3ff_ffff;
+        h[1] += c;
+    }
+
+    // Final reduction mod 2^130 - 5.
+    let mut c = h[1] >> 26;
+    h[1] &= 0x3ff_ffff;
+    h[2] += c;
+    c = h[2] >> 26;
+    h[2] &= 0x3ff_ffff;
+    h[3] += c;
+    c = h[3] >> 26;
+    h[3] &= 0x3ff_ffff;
+    h[4] += c;
+    c = h[4] >> 26;
+    h[4] &= 0x3ff_ffff;
+    h[0] += c * 5;
+    c = h[0] >> 26;
+    h[0] &= 0x3ff_ffff;
+    h[1] += c;
+
+    // Compute h + -p and select it if h >= p.
+    let mut g = [0u32; 5];
+    g[0] = h[0].wrapping_add(5);
+    c = g[0] >> 26;
+    g[0] &= 0x3ff_ffff;
+    g[1] = h[1] + c;
+    c = g[1] >> 26;
+    g[1] &= 0x3ff_ffff;
+    g[2] = h[2] + c;
+    c = g[2] >> 26;
+    g[2] &= 0x3ff_ffff;
+    g[3] = h[3] + c;
+    c = g[3] >> 26;
+    g[3] &= 0x3ff_ffff;
+    g[4] = h[4].wrapping_add(c).wrapping_sub(1 << 26);
+
+    let mask = (g[4] >> 31).wrapping_sub(1);
+    for i in 0..5 {
+        g[i] &= mask;
+        h[i] &= !mask;
+        h[i] |= g[i];
+    }
+
+    // Serialize h as a little-endian 128-bit number and add s.
+    let mut f0 = (h[0] | (h[1] << 26)) as u64;
+    let mut f1 = ((h[1] >> 6) | (h[2] << 20)) as u64;
+    let mut f2 = ((h[2] >> 12) | (h[3] << 14)) as u64;
+    let mut f3 = ((h[3] >> 18) | (h[4] << 8)) as u64;
+
+    f0 += u32::from_le_bytes([key[16], key[17], key[18], key[19]]) as u64;
+    f1 += u32::from_le_bytes([key[20], key[21], key[22], key[23]]) as u64 + (f0 >> 32);
+    f2 += u32::from_le_bytes([key[24], key[25], key[26], key[27]]) as u64 + (f1 >> 32);
+    f3 += u32::from_le_bytes([key[28], key[29], key[30], key[31]]) as u64 + (f2 >> 32);
+
+    let mut tag = [0u8; 16];
+    tag[0..4].copy_from_slice(&(f0 as u32).to_le_bytes());
+    tag[4..8].copy_from_slice(&(f1 as u32).to_le_bytes());
+    tag[8..12].copy_from_slice(&(f2 as u32).to_le_bytes());
+    tag[12..16].copy_from_slice(&(f3 as u32).to_le_bytes());
+    tag
+}
+
+/// One limb of the h*r schoolbook multiply with the 2^130-5 fold-in.
+fn mul(h: [u32; 5], r: [u32; 5], i: usize) -> u64 {
+    let s: [u32; 5] = [r[0], r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+    let mut acc = 0u64;
+    for j in 0..5 {
+        let rv = if j <= i { r[i - j] } else { s[5 + i - j] };
+        acc += h[j] as u64 * rv as u64;
+    }
+    acc
+}
+
+/// Derive the one-time Poly1305 key from the first ChaCha20 keystream block
+/// (counter 0), as specified by RFC 8439.
+fn poly1305_key(key: &[u8; 32], nonce: &[u8; 12]) -> [u8; 32] {
+    let block = chacha20_block(key, 0, nonce);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&block[..32]);
+    out
+}
+
+/// Build the Poly1305 input: `aad || pad16 || ciphertext || pad16 ||
+/// len(aad) || len(ciphertext)`, all lengths little-endian u64.
+fn mac_data(aad: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(aad.len() + ciphertext.len() + 32);
+    data.extend_from_slice(aad);
+    pad16(&mut data);
+    data.extend_from_slice(ciphertext);
+    pad16(&mut data);
+    data.extend_from_slice(&(aad.len() as u64).to_le_bytes());
+    data.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    data
+}
+
+fn pad16(v: &mut Vec<u8>) {
+    let rem = v.len() % 16;
+    if rem != 0 {
+        v.resize(v.len() + (16 - rem), 0);
+    }
+}
+
+/// Encrypt `plaintext` with `aad` bound in, returning `ciphertext || tag`.
+pub fn seal(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let poly_key = poly1305_key(key, nonce);
+    let mut ciphertext = plaintext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut ciphertext);
+    let tag = poly1305_mac(&poly_key, &mac_data(aad, &ciphertext));
+    ciphertext.extend_from_slice(&tag);
+    ciphertext
+}
+
+/// Verify and decrypt `ciphertext || tag`. Returns the plaintext only if the
+/// Poly1305 tag matches in constant time.
+pub fn open(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], body: &[u8]) -> Result<Vec<u8>, String> {
+    if body.len() < 16 {
+        return Err("ciphertext too short for tag".to_string());
+    }
+    let (ciphertext, tag) = body.split_at(body.len() - 16);
+    let poly_key = poly1305_key(key, nonce);
+    let expected = poly1305_mac(&poly_key, &mac_data(aad, ciphertext));
+    if !constant_time_eq(tag, &expected) {
+        return Err("Poly1305 tag mismatch".to_string());
+    }
+    let mut plaintext = ciphertext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut plaintext);
+    Ok(plaintext)
+}
+
+/// Constant-time byte-slice comparison to avoid leaking the tag via timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    diff == 0
+}
+
+/// Generate a fresh 96-bit nonce straight from the OS CSPRNG. Nonce reuse
+/// under the same key breaks both ChaCha20 (keystream reuse) and Poly1305
+/// (one-time-key reuse enables forgery), so this must never fall back to a
+/// weaker source.
+pub fn random_nonce() -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    read_os_random(&mut nonce).expect("failed to read OS randomness for nonce");
+    nonce
+}
+
+/// Fill `buf` with cryptographically secure random bytes from `/dev/urandom`.
+fn read_os_random(buf: &mut [u8]) -> std::io::Result<()> {
+    use std::fs::File;
+    use std::io::Read;
+
+    File::open("/dev/urandom")?.read_exact(buf)
+}
+
+/// Parse a 64-hex-char string into a 32-byte key.
+pub fn parse_key(hex: &str) -> Result<[u8; 32], String> {
+    let hex = hex.trim();
+    if hex.len() != 64 {
+        return Err(format!("key must be 64 hex chars, got {}", hex.len()));
+    }
+    let mut key = [0u8; 32];
+    for i in 0..32 {
+        key[i] = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+            .map_err(|_| "key contains non-hex characters".to_string())?;
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode a hex string into bytes (test-only; inputs are fixed literals).
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// RFC 8439 §2.8.2 ChaCha20-Poly1305 AEAD test vector.
+    #[test]
+    fn seal_matches_rfc8439_vector() {
+        let key: [u8; 32] = hex(concat!(
+            "808182838485868788898a8b8c8d8e8f",
+            "909192939495969798999a9b9c9d9e9f"
+        ))
+        .try_into()
+        .unwrap();
+        let nonce: [u8; 12] = hex("070000004041424344454647").try_into().unwrap();
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let plaintext = b"Ladies and Gentlemen of the class of '99: \
+If I could offer you only one tip for the future, sunscreen would be it.";
+        let expected = hex(concat!(
+            "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d",
+            "63dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36",
+            "92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc3",
+            "ff4def08e4b7a9de576d26586cec64b61161ae10b594f09e26a7e902ecbd0600691"
+        ));
+
+        let sealed = seal(&key, &nonce, &aad, plaintext);
+        assert_eq!(sealed, expected);
+
+        let opened = open(&key, &nonce, &aad, &sealed).unwrap();
+        assert_eq!(opened, plaintext);
+    }
+
+    #[test]
+    fn open_rejects_tampered_ciphertext() {
+        let key = [0x42u8; 32];
+        let nonce = [0x24u8; 12];
+        let aad = b"header";
+        let mut sealed = seal(&key, &nonce, aad, b"relay status payload");
+        // Flip a ciphertext byte (the tag is the last 16 bytes).
+        sealed[0] ^= 0x01;
+
+        assert!(open(&key, &nonce, aad, &sealed).is_err());
+    }
+}
@@ -0,0 +1,508 @@
+//! WebSocket gateway that bridges the binary TCP relay protocol to JSON over
+//! WebSocket, so the relay board can be driven from browsers or clients behind
+//! NAT without exposing the raw port.
+//!
+//! One pooled [`RelayClient`] connection is shared by every WebSocket client;
+//! `monitor` transitions are fanned out over a broadcast channel so several
+//! remote dashboards can watch and control the same board at once. Like the
+//! rest of the tool the WebSocket handshake (SHA-1 + base64) and the tiny JSON
+//! codec are hand-rolled rather than taken from additional crates.
+
+use std::collections::HashMap;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+
+// `remote.rs` is compiled twice: once as the `remote` CLI binary, once here
+// as a vendored module. Items the CLI uses but the gateway doesn't (and vice
+// versa) are expected, so dead-code analysis is silenced for the whole tree.
+#[path = "remote.rs"]
+#[allow(dead_code)]
+mod relay;
+
+use relay::{RelayClient, RelayMode, RelayMonitor, Transition};
+
+/// Listen address used when the operator does not name one.
+const DEFAULT_LISTEN: &str = "127.0.0.1:9000";
+
+/// RFC 6455 magic GUID concatenated to the client key before hashing.
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest WebSocket frame payload the gateway will allocate for. Control
+/// messages are a handful of bytes; this is generous headroom without
+/// trusting a client-supplied extended length outright.
+const MAX_WS_FRAME_LEN: usize = 64 * 1024;
+
+/// A minimal JSON value covering the flat control-message and response shapes
+/// the gateway exchanges. Nested objects/arrays are intentionally unsupported.
+enum Json {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    Null,
+}
+
+impl Json {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_u8(&self) -> Option<u8> {
+        match self {
+            Json::Num(n) => Some(*n as u8),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a flat JSON object (`{"k": <str|num|bool|null>, ...}`) into a map.
+/// Returns `None` on a missing key, colon or separator, an unterminated
+/// string, or a nested value. A trailing comma before `}` and any bytes after
+/// the closing `}` are accepted.
+fn parse_object(input: &str) -> Option<HashMap<String, Json>> {
+    let bytes = input.as_bytes();
+    let mut i = 0;
+    let skip_ws = |i: &mut usize| {
+        while *i < bytes.len() && (bytes[*i] as char).is_whitespace() {
+            *i += 1;
+        }
+    };
+
+    skip_ws(&mut i);
+    if i >= bytes.len() || bytes[i] != b'{' {
+        return None;
+    }
+    i += 1;
+
+    let mut map = HashMap::new();
+    loop {
+        skip_ws(&mut i);
+        if i < bytes.len() && bytes[i] == b'}' {
+            return Some(map);
+        }
+        let key = parse_string(bytes, &mut i)?;
+        skip_ws(&mut i);
+        if i >= bytes.len() || bytes[i] != b':' {
+            return None;
+        }
+        i += 1;
+        skip_ws(&mut i);
+        let value = parse_value(bytes, &mut i)?;
+        map.insert(key, value);
+        skip_ws(&mut i);
+        match bytes.get(i) {
+            Some(b',') => i += 1,
+            Some(b'}') => return Some(map),
+            _ => return None,
+        }
+    }
+}
+
+fn parse_string(bytes: &[u8], i: &mut usize) -> Option<String> {
+    if bytes.get(*i) != Some(&b'"') {
+        return None;
+    }
+    *i += 1;
+    let start = *i;
+    while *i < bytes.len() && bytes[*i] != b'"' {
+        // No escape handling needed for the small key/value set we accept.
+        *i += 1;
+    }
+    if *i >= bytes.len() {
+        return None;
+    }
+    let s = String::from_utf8_lossy(&bytes[start..*i]).into_owned();
+    *i += 1;
+    Some(s)
+}
+
+fn parse_value(bytes: &[u8], i: &mut usize) -> Option<Json> {
+    match bytes.get(*i)? {
+        b'"' => parse_string(bytes, i).map(Json::Str),
+        b't' if bytes[*i..].starts_with(b"true") => {
+            *i += 4;
+            Some(Json::Bool(true))
+        }
+        b'f' if bytes[*i..].starts_with(b"false") => {
+            *i += 5;
+            Some(Json::Bool(false))
+        }
+        b'n' if bytes[*i..].starts_with(b"null") => {
+            *i += 4;
+            Some(Json::Null)
+        }
+        _ => {
+            let start = *i;
+            while *i < bytes.len() && matches!(bytes[*i], b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E') {
+                *i += 1;
+            }
+            std::str::from_utf8(&bytes[start..*i])
+                .ok()?
+                .parse::<f64>()
+                .ok()
+                .map(Json::Num)
+        }
+    }
+}
+
+/// Translate one decoded control message into a backend call and return the
+/// JSON reply mirroring `RespType` (`ok`/`err`/`status`/`pong`).
+async fn handle_message(client: &RelayClient, msg: &HashMap<String, Json>) -> String {
+    let cmd = match msg.get("cmd").and_then(Json::as_str) {
+        Some(c) => c,
+        None => return error_json("missing \"cmd\" field"),
+    };
+
+    match cmd {
+        "ping" => match client.ping().await {
+            Ok(()) => "{\"type\":\"pong\"}".to_string(),
+            Err(e) => error_json(&e.to_string()),
+        },
+        "status" => match client.get_status().await {
+            Ok(status) => status_json(status, 8),
+            Err(e) => error_json(&e.to_string()),
+        },
+        "set" => {
+            let relay = msg.get("relay").and_then(Json::as_u8);
+            let state = msg.get("state").and_then(Json::as_bool);
+            match (relay, state) {
+                (Some(relay), Some(state)) => device_call(client.set_relay(relay, state).await),
+                _ => error_json("\"set\" requires \"relay\" and \"state\""),
+            }
+        }
+        "toggle" => match msg.get("relay").and_then(Json::as_u8) {
+            Some(relay) => device_call(client.toggle_relay(relay).await),
+            None => error_json("\"toggle\" requires \"relay\""),
+        },
+        "all" => match msg.get("mask").and_then(Json::as_u8) {
+            Some(mask) => device_call(client.set_all(mask).await),
+            None => error_json("\"all\" requires \"mask\""),
+        },
+        // Subscription is handled by the caller (it flips the fan-out flag).
+        "subscribe" => "{\"type\":\"ok\"}".to_string(),
+        other => error_json(&format!("unknown cmd: {}", other)),
+    }
+}
+
+/// Map a device call result to `{"type":"ok"}` or an `err` object. Device
+/// errors carry their `0x..` code through in `error_code`.
+fn device_call(result: Result<(), Box<dyn std::error::Error + Send + Sync>>) -> String {
+    match result {
+        Ok(()) => "{\"type\":\"ok\"}".to_string(),
+        Err(e) => {
+            let msg = e.to_string();
+            if let Some(code) = msg.strip_prefix("Device error: ") {
+                format!("{{\"type\":\"err\",\"error_code\":\"{}\"}}", code)
+            } else {
+                error_json(&msg)
+            }
+        }
+    }
+}
+
+fn status_json(status: u8, num_relays: usize) -> String {
+    let relays: Vec<String> = (0..num_relays)
+        .map(|i| format!("{{\"id\":{},\"on\":{}}}", i, (status >> i) & 1 == 1))
+        .collect();
+    format!(
+        "{{\"type\":\"status\",\"relays\":[{}],\"raw\":\"0x{:02X}\"}}",
+        relays.join(","),
+        status
+    )
+}
+
+fn error_json(message: &str) -> String {
+    format!("{{\"type\":\"err\",\"error\":\"{}\"}}", escape(message))
+}
+
+/// A transition pushed to subscribed clients as a discrete status event.
+fn transition_json(t: &Transition) -> String {
+    format!(
+        "{{\"type\":\"event\",\"relay\":{},\"on\":{}}}",
+        t.relay_id, t.on
+    )
+}
+
+/// Escape backslashes and double quotes for a bare JSON string literal.
+/// Control characters are passed through unescaped.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Serve one WebSocket client: complete the handshake, then shuttle control
+/// messages to the backend and fan out broadcast transitions.
+async fn handle_conn(
+    stream: tokio::net::TcpStream,
+    client: RelayClient,
+    mut events: broadcast::Receiver<Transition>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (mut rd, mut wr) = stream.into_split();
+    ws_handshake(&mut rd, &mut wr).await?;
+
+    let mut subscribed = false;
+    loop {
+        tokio::select! {
+            frame = read_ws_frame(&mut rd) => {
+                match frame? {
+                    // Text frame: a control message.
+                    Some((0x1, payload)) => {
+                        let text = String::from_utf8_lossy(&payload);
+                        let reply = match parse_object(&text) {
+                            Some(msg) => {
+                                if msg.get("cmd").and_then(Json::as_str) == Some("subscribe") {
+                                    subscribed = true;
+                                }
+                                handle_message(&client, &msg).await
+                            }
+                            None => error_json("invalid JSON"),
+                        };
+                        write_ws_text(&mut wr, &reply).await?;
+                    }
+                    // Close frame or connection gone.
+                    Some((0x8, _)) | None => return Ok(()),
+                    // Ping/pong and other opcodes are ignored.
+                    Some(_) => {}
+                }
+            }
+            ev = events.recv() => {
+                match ev {
+                    Ok(t) if subscribed => write_ws_text(&mut wr, &transition_json(&t)).await?,
+                    // Lagged or not subscribed: drop the event for this client.
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Read the HTTP upgrade request, compute the accept key, and send the 101
+/// switching-protocols response.
+async fn ws_handshake(
+    rd: &mut OwnedReadHalf,
+    wr: &mut OwnedWriteHalf,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut request = Vec::new();
+    let mut byte = [0u8; 1];
+    // Read headers until the blank line terminating the request.
+    while !request.ends_with(b"\r\n\r\n") {
+        let n = rd.read(&mut byte).await?;
+        if n == 0 {
+            return Err("client closed during handshake".into());
+        }
+        request.push(byte[0]);
+        if request.len() > 8192 {
+            return Err("handshake request too large".into());
+        }
+    }
+
+    let text = String::from_utf8_lossy(&request);
+    let key = text
+        .lines()
+        .find_map(|l| l.strip_prefix("Sec-WebSocket-Key:"))
+        .map(|v| v.trim())
+        .ok_or("missing Sec-WebSocket-Key")?;
+
+    let accept = base64_encode(&sha1(format!("{}{}", key, WS_GUID).as_bytes()));
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept
+    );
+    wr.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Read one WebSocket frame, returning `(opcode, unmasked payload)` or `None`
+/// at end of stream. Only the bits the gateway cares about are decoded.
+async fn read_ws_frame(
+    rd: &mut OwnedReadHalf,
+) -> Result<Option<(u8, Vec<u8>)>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut head = [0u8; 2];
+    if rd.read_exact(&mut head).await.is_err() {
+        return Ok(None);
+    }
+    let opcode = head[0] & 0x0F;
+    let masked = head[1] & 0x80 != 0;
+
+    let mut len = (head[1] & 0x7F) as usize;
+    if len == 126 {
+        let mut ext = [0u8; 2];
+        rd.read_exact(&mut ext).await?;
+        len = u16::from_be_bytes(ext) as usize;
+    } else if len == 127 {
+        let mut ext = [0u8; 8];
+        rd.read_exact(&mut ext).await?;
+        len = u64::from_be_bytes(ext) as usize;
+    }
+    if len > MAX_WS_FRAME_LEN {
+        return Err(format!("frame payload too large: {} bytes", len).into());
+    }
+
+    let mut mask = [0u8; 4];
+    if masked {
+        rd.read_exact(&mut mask).await?;
+    }
+
+    let mut payload = vec![0u8; len];
+    rd.read_exact(&mut payload).await?;
+    if masked {
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+    }
+    Ok(Some((opcode, payload)))
+}
+
+/// Write an unmasked server-to-client text frame.
+async fn write_ws_text(
+    wr: &mut OwnedWriteHalf,
+    text: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let payload = text.as_bytes();
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x81); // FIN + text opcode.
+    if payload.len() < 126 {
+        frame.push(payload.len() as u8);
+    } else if payload.len() <= u16::MAX as usize {
+        frame.push(126);
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(payload);
+    wr.write_all(&frame).await?;
+    Ok(())
+}
+
+/// SHA-1 digest (RFC 3174), hand-rolled for the WebSocket accept key.
+fn sha1(msg: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+    let mut data = msg.to_vec();
+    let bit_len = (msg.len() as u64) * 8;
+    data.push(0x80);
+    while data.len() % 64 != 56 {
+        data.push(0);
+    }
+    data.extend_from_slice(&bit_len.to_be_bytes());
+
+    for block in data.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | ((!b) & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let tmp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = tmp;
+        }
+
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut out = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        out[4 * i..4 * i + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+/// Standard base64 encoding (with padding) of a byte slice.
+fn base64_encode(data: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        out.push(TABLE[(n >> 18) as usize & 0x3F] as char);
+        out.push(TABLE[(n >> 12) as usize & 0x3F] as char);
+        out.push(if chunk.len() > 1 { TABLE[(n >> 6) as usize & 0x3F] as char } else { '=' });
+        out.push(if chunk.len() > 2 { TABLE[n as usize & 0x3F] as char } else { '=' });
+    }
+    out
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (key, args) = relay::extract_key(std::env::args().collect())?;
+
+    if args.len() < 2 {
+        eprintln!("Usage: {} [--key <hex>] <device host:port> [listen addr]", args[0]);
+        return Ok(());
+    }
+    let device = args[1].clone();
+    let listen = args.get(2).cloned().unwrap_or_else(|| DEFAULT_LISTEN.to_string());
+
+    // One pooled backend connection, shared by every WebSocket client.
+    let client = match key {
+        Some(key) => RelayClient::with_key(&device, key),
+        None => RelayClient::new(&device),
+    };
+
+    // A single monitor subscription feeds the broadcast fan-out.
+    let mode = match key {
+        Some(key) => RelayMode::Encrypted(key),
+        None => RelayMode::Plain,
+    };
+    let (monitor, mut transitions) = RelayMonitor::subscribe(&device, mode, 256);
+    let _ = &monitor; // Keep the subscription alive for the process lifetime.
+    let (bcast, _) = broadcast::channel::<Transition>(256);
+    let bcast_tx = bcast.clone();
+    tokio::spawn(async move {
+        while let Some(t) = transitions.recv().await {
+            let _ = bcast_tx.send(t);
+        }
+    });
+
+    let listener = TcpListener::bind(&listen).await?;
+    println!("Gateway bridging {} <-> ws://{}", device, listen);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let client = client.clone();
+        let events = bcast.subscribe();
+        tokio::spawn(async move {
+            if let Err(e) = handle_conn(stream, client, events).await {
+                eprintln!("connection error: {}", e);
+            }
+        });
+    }
+}
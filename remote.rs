@@ -1,23 +1,41 @@
-use std::io::{Read, Write};
-use std::net::TcpStream;
-use std::time::Duration;
+use std::collections::VecDeque;
 use std::convert::TryFrom;
+use std::time::Duration;
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::sleep;
+
+mod crypto;
 
 const MAGIC: u8 = 0xA5;
 
+/// Probe byte appended after `MAGIC` in a discovery datagram. Devices that see
+/// `[MAGIC, DISCOVERY_PROBE]` answer with their identity payload.
+const DISCOVERY_PROBE: u8 = 0x7F;
+
+/// Relay port used when the user runs `discover` without naming one.
+const DEFAULT_PORT: u16 = 8266;
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
-enum Cmd {
+pub enum Cmd {
     Ping = 0x01,
     GetStatus = 0x02,
     SetRelay = 0x03,
     ToggleRelay = 0x04,
     SetAll = 0x05,
+    Subscribe = 0x06,
 }
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq)]
-enum RespType {
+pub enum RespType {
     Ok,
     Err,
     Status,
@@ -38,70 +56,220 @@ impl TryFrom<u8> for RespType {
     }
 }
 
-struct RelayClient {
-    addr: String,
-    timeout: Duration,
+/// Transport mode for [`RelayClient`]. Legacy firmware speaks the bare
+/// plaintext framing; newer devices sharing a key speak the authenticated
+/// ChaCha20-Poly1305 framing.
+#[derive(Clone)]
+pub enum RelayMode {
+    Plain,
+    Encrypted([u8; 32]),
 }
 
-impl RelayClient {
-    fn new(addr: &str) -> Self {
-        Self {
-            addr: addr.to_string(),
-            timeout: Duration::from_secs(2),
+/// Encode a request into its wire frame for the given transport mode.
+fn encode_request(mode: &RelayMode, cmd: Cmd, relay_id: u8, value: u8) -> Vec<u8> {
+    match mode {
+        RelayMode::Plain => vec![MAGIC, cmd as u8, relay_id, value],
+        RelayMode::Encrypted(key) => {
+            // Encrypted framing: [MAGIC][12-byte nonce][ciphertext][16-byte tag].
+            let nonce = crypto::random_nonce();
+            let sealed = crypto::seal(key, &nonce, &[MAGIC], &[cmd as u8, relay_id, value]);
+            let mut packet = Vec::with_capacity(1 + 12 + sealed.len());
+            packet.push(MAGIC);
+            packet.extend_from_slice(&nonce);
+            packet.extend_from_slice(&sealed);
+            packet
         }
     }
-    
-    fn send_command(&self, cmd: Cmd, relay_id: u8, value: u8) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let mut stream = TcpStream::connect(&self.addr)?;
-        stream.set_read_timeout(Some(self.timeout))?;
-        stream.set_write_timeout(Some(self.timeout))?;
-        
-        // Send request
-        let packet = [MAGIC, cmd as u8, relay_id, value];
-        stream.write_all(&packet)?;
-        
-        // Read response
-        let mut response = vec![0u8; 64];
-        let n = stream.read(&mut response)?;
-        response.truncate(n);
-        
-        // Validate magic byte
-        if response.is_empty() || response[0] != MAGIC {
-            return Err("Invalid response magic byte".into());
-        }
-        
-        Ok(response)
+}
+
+/// Decode a framed response payload (the bytes after `MAGIC`, and after the
+/// `u16` length in encrypted mode, as returned by [`FrameReader::next_frame`]) into a
+/// plaintext-style buffer (`[MAGIC, resp_type, ..]`) so the parsing helpers stay
+/// identical across both transports.
+fn decode_response(mode: &RelayMode, payload: &[u8]) -> Result<Vec<u8>, String> {
+    match mode {
+        RelayMode::Plain => {
+            let mut out = Vec::with_capacity(1 + payload.len());
+            out.push(MAGIC);
+            out.extend_from_slice(payload);
+            Ok(out)
+        }
+        RelayMode::Encrypted(key) => {
+            // Payload layout: [12-byte nonce][ciphertext][16-byte tag].
+            if payload.len() < 12 + 16 {
+                return Err("encrypted frame too short".to_string());
+            }
+            let mut resp_nonce = [0u8; 12];
+            resp_nonce.copy_from_slice(&payload[..12]);
+            // Verify the tag in constant time before trusting any of the body.
+            let plaintext = crypto::open(key, &resp_nonce, &[MAGIC], &payload[12..])?;
+            let mut out = Vec::with_capacity(1 + plaintext.len());
+            out.push(MAGIC);
+            out.extend_from_slice(&plaintext);
+            Ok(out)
+        }
     }
-    
-    fn ping(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let resp = self.send_command(Cmd::Ping, 0, 0)?;
-        
+}
+
+/// An incremental reader that accumulates bytes off the socket into a growable
+/// circular buffer and hands back one complete frame at a time, tolerating
+/// partial reads and leftover bytes belonging to the next frame. Encrypted
+/// frames are `[MAGIC][u16 len][payload]`; legacy plaintext frames have no
+/// length prefix at all, so [`FrameReader::next_frame`] branches on the
+/// [`RelayMode`] to know how to find the frame boundary.
+struct FrameReader {
+    buf: VecDeque<u8>,
+}
+
+impl FrameReader {
+    fn new() -> Self {
+        Self { buf: VecDeque::new() }
+    }
+
+    /// Append freshly-read bytes to the tail of the buffer.
+    fn feed(&mut self, data: &[u8]) {
+        self.buf.extend(data.iter().copied());
+    }
+
+    /// Copy the first `n` buffered bytes without consuming them.
+    fn peek(&self, n: usize) -> Option<Vec<u8>> {
+        if self.buf.len() < n {
+            return None;
+        }
+        Some(self.buf.iter().take(n).copied().collect())
+    }
+
+    /// Remove and return exactly `n` bytes, or `None` if fewer are buffered.
+    fn take_exact(&mut self, n: usize) -> Option<Vec<u8>> {
+        if self.buf.len() < n {
+            return None;
+        }
+        Some((0..n).map(|_| self.buf.pop_front().unwrap()).collect())
+    }
+
+    /// Try to extract the next complete frame's payload. Returns `Ok(None)`
+    /// when more bytes are still needed, and an error if the header is corrupt.
+    fn next_frame(&mut self, mode: &RelayMode) -> Result<Option<Vec<u8>>, String> {
+        match mode {
+            // Legacy firmware speaks a bare, unframed response with no length
+            // prefix, so the frame boundary has to come from the response
+            // type byte itself: Ok/Pong carry no payload (`[MAGIC,
+            // resp_type]`), Status/Err carry two more bytes.
+            RelayMode::Plain => {
+                let header = match self.peek(2) {
+                    Some(h) => h,
+                    None => return Ok(None),
+                };
+                if header[0] != MAGIC {
+                    return Err("Invalid response magic byte".to_string());
+                }
+                let frame_len = match RespType::try_from(header[1]) {
+                    Ok(RespType::Ok) | Ok(RespType::Pong) => 2,
+                    Ok(RespType::Status) | Ok(RespType::Err) => 4,
+                    Err(_) => return Err("Unknown response type".to_string()),
+                };
+                if self.buf.len() < frame_len {
+                    return Ok(None);
+                }
+                let frame = self.take_exact(frame_len).unwrap();
+                Ok(Some(frame[1..].to_vec()))
+            }
+            // Header is MAGIC + a big-endian u16 length.
+            RelayMode::Encrypted(_) => {
+                let header = match self.peek(3) {
+                    Some(h) => h,
+                    None => return Ok(None),
+                };
+                if header[0] != MAGIC {
+                    return Err("Invalid response magic byte".to_string());
+                }
+                let len = u16::from_be_bytes([header[1], header[2]]) as usize;
+                if self.buf.len() < 3 + len {
+                    return Ok(None);
+                }
+                self.take_exact(3);
+                Ok(Some(self.take_exact(len).unwrap()))
+            }
+        }
+    }
+}
+
+/// A command queued for the connection task, along with the waiter it must be
+/// routed back to.
+struct Request {
+    cmd: Cmd,
+    relay_id: u8,
+    value: u8,
+    /// Idempotent requests (`Ping`/`GetStatus`) are transparently retried
+    /// across a reconnect; everything else fails fast.
+    idempotent: bool,
+    responder: oneshot::Sender<Result<Vec<u8>, String>>,
+}
+
+/// A handle to the long-lived connection task. Cloning the handle lets many
+/// callers submit commands concurrently over the one shared socket.
+#[derive(Clone)]
+pub struct RelayClient {
+    tx: mpsc::Sender<Request>,
+}
+
+impl RelayClient {
+    pub fn new(addr: &str) -> Self {
+        Self::spawn(addr.to_string(), Duration::from_secs(2), RelayMode::Plain)
+    }
+
+    /// Enable authenticated encryption with the given shared 32-byte key.
+    pub fn with_key(addr: &str, key: [u8; 32]) -> Self {
+        Self::spawn(addr.to_string(), Duration::from_secs(2), RelayMode::Encrypted(key))
+    }
+
+    fn spawn(addr: String, timeout: Duration, mode: RelayMode) -> Self {
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(connection_task(addr, timeout, mode, rx));
+        Self { tx }
+    }
+
+    /// Submit one command to the connection task and await its response.
+    async fn send_command(&self, cmd: Cmd, relay_id: u8, value: u8) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+        let idempotent = matches!(cmd, Cmd::Ping | Cmd::GetStatus);
+        let (responder, rx) = oneshot::channel();
+        self.tx
+            .send(Request { cmd, relay_id, value, idempotent, responder })
+            .await
+            .map_err(|_| "connection task is gone")?;
+        let resp = rx.await.map_err(|_| "connection task dropped the request")?;
+        resp.map_err(|e| e.into())
+    }
+
+    pub async fn ping(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let resp = self.send_command(Cmd::Ping, 0, 0).await?;
+
         if resp.len() >= 2 {
             let resp_type = RespType::try_from(resp[1])?;
             if resp_type == RespType::Pong {
                 return Ok(());
             }
         }
-        
+
         Err("Invalid ping response".into())
     }
-    
-    fn get_status(&self) -> Result<u8, Box<dyn std::error::Error>> {
-        let resp = self.send_command(Cmd::GetStatus, 0, 0)?;
-        
+
+    pub async fn get_status(&self) -> Result<u8, Box<dyn std::error::Error + Send + Sync>> {
+        let resp = self.send_command(Cmd::GetStatus, 0, 0).await?;
+
         if resp.len() >= 4 {
             let resp_type = RespType::try_from(resp[1])?;
             if resp_type == RespType::Status {
                 return Ok(resp[3]);
             }
         }
-        
+
         Err("Invalid status response".into())
     }
-    
-    fn set_relay(&self, relay_id: u8, state: bool) -> Result<(), Box<dyn std::error::Error>> {
-        let resp = self.send_command(Cmd::SetRelay, relay_id, state as u8)?;
-        
+
+    pub async fn set_relay(&self, relay_id: u8, state: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let resp = self.send_command(Cmd::SetRelay, relay_id, state as u8).await?;
+
         if resp.len() >= 2 {
             let resp_type = RespType::try_from(resp[1])?;
             if resp_type == RespType::Ok {
@@ -111,13 +279,13 @@ impl RelayClient {
                 return Err(format!("Device error: 0x{:02X}", error_code).into());
             }
         }
-        
+
         Err("Invalid response".into())
     }
-    
-    fn toggle_relay(&self, relay_id: u8) -> Result<(), Box<dyn std::error::Error>> {
-        let resp = self.send_command(Cmd::ToggleRelay, relay_id, 0)?;
-        
+
+    pub async fn toggle_relay(&self, relay_id: u8) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let resp = self.send_command(Cmd::ToggleRelay, relay_id, 0).await?;
+
         if resp.len() >= 2 {
             let resp_type = RespType::try_from(resp[1])?;
             if resp_type == RespType::Ok {
@@ -127,24 +295,427 @@ impl RelayClient {
                 return Err(format!("Device error: 0x{:02X}", error_code).into());
             }
         }
-        
+
         Err("Invalid response".into())
     }
-    
-    fn set_all(&self, bitmask: u8) -> Result<(), Box<dyn std::error::Error>> {
-        let resp = self.send_command(Cmd::SetAll, bitmask, 0)?;
-        
+
+    pub async fn set_all(&self, bitmask: u8) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let resp = self.send_command(Cmd::SetAll, bitmask, 0).await?;
+
         if resp.len() >= 2 {
             let resp_type = RespType::try_from(resp[1])?;
             if resp_type == RespType::Ok {
                 return Ok(());
             }
         }
-        
+
         Err("Invalid response".into())
     }
 }
 
+/// An in-flight request the connection task is still waiting on a response for.
+struct Pending {
+    cmd: Cmd,
+    relay_id: u8,
+    value: u8,
+    idempotent: bool,
+    responder: oneshot::Sender<Result<Vec<u8>, String>>,
+}
+
+/// Fail in-flight requests on a dropped connection: non-idempotent ones get a
+/// clear error, idempotent ones are kept so they can be transparently resent
+/// once the socket is re-established.
+fn drop_connection(pending: &mut VecDeque<Pending>) {
+    let mut kept = VecDeque::new();
+    for p in pending.drain(..) {
+        if p.idempotent {
+            kept.push_back(p);
+        } else {
+            let _ = p.responder.send(Err("connection lost before response".to_string()));
+        }
+    }
+    *pending = kept;
+}
+
+/// The single task that owns the socket. Commands arrive on `rx`; responses are
+/// routed back to waiters in FIFO order. On any I/O error or timeout it
+/// reconnects with exponential backoff.
+async fn connection_task(
+    addr: String,
+    timeout: Duration,
+    mode: RelayMode,
+    mut rx: mpsc::Receiver<Request>,
+) {
+    let mut pending: VecDeque<Pending> = VecDeque::new();
+    let mut stream: Option<TcpStream> = None;
+    let mut reader = FrameReader::new();
+    let mut backoff = Duration::from_millis(100);
+    let max_backoff = Duration::from_secs(5);
+
+    loop {
+        // (Re)establish the connection before servicing reads/writes.
+        if stream.is_none() {
+            match TcpStream::connect(&addr).await {
+                Ok(s) => {
+                    backoff = Duration::from_millis(100);
+                    stream = Some(s);
+                    // Resend idempotent requests that survived the drop.
+                    let sock = stream.as_mut().unwrap();
+                    let mut resend_failed = false;
+                    for p in pending.iter() {
+                        let packet = encode_request(&mode, p.cmd, p.relay_id, p.value);
+                        if sock.write_all(&packet).await.is_err() {
+                            resend_failed = true;
+                            break;
+                        }
+                    }
+                    if resend_failed {
+                        stream = None;
+                        drop_connection(&mut pending);
+                        continue;
+                    }
+                }
+                Err(_) => {
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(max_backoff);
+                    continue;
+                }
+            }
+        }
+
+        // Tear-down is deferred until after the `sock` borrow is released so we
+        // can reassign `stream` without fighting the borrow checker.
+        let mut reconnect = false;
+        {
+            let sock = stream.as_mut().unwrap();
+            let mut buf = vec![0u8; 256];
+
+            tokio::select! {
+                maybe_req = rx.recv() => {
+                    match maybe_req {
+                        // All handles dropped: nothing left to serve.
+                        None => return,
+                        Some(req) => {
+                            let packet = encode_request(&mode, req.cmd, req.relay_id, req.value);
+                            let pend = Pending {
+                                cmd: req.cmd,
+                                relay_id: req.relay_id,
+                                value: req.value,
+                                idempotent: req.idempotent,
+                                responder: req.responder,
+                            };
+                            pending.push_back(pend);
+                            if sock.write_all(&packet).await.is_err() {
+                                reconnect = true;
+                            }
+                        }
+                    }
+                }
+                read = tokio::time::timeout(timeout, sock.read(&mut buf)), if !pending.is_empty() => {
+                    match read {
+                        // EOF, I/O error, or timeout: tear down and reconnect.
+                        Ok(Ok(0)) | Ok(Err(_)) | Err(_) => reconnect = true,
+                        Ok(Ok(n)) => {
+                            reader.feed(&buf[..n]);
+                            // A single read may complete zero, one, or several
+                            // frames; drain every frame now buffered.
+                            loop {
+                                match reader.next_frame(&mode) {
+                                    Ok(Some(payload)) => {
+                                        if let Some(p) = pending.pop_front() {
+                                            let _ = p.responder.send(decode_response(&mode, &payload));
+                                        }
+                                    }
+                                    Ok(None) => break,
+                                    Err(_) => {
+                                        reconnect = true;
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if reconnect {
+            stream = None;
+            reader = FrameReader::new();
+            drop_connection(&mut pending);
+        }
+    }
+}
+
+/// A single observed relay transition, stamped with the local time it was seen.
+#[derive(Clone, Copy)]
+pub struct Transition {
+    pub at: SystemTime,
+    pub relay_id: u8,
+    pub on: bool,
+}
+
+/// Fixed-capacity ring of recent [`Transition`]s. Oldest entries are
+/// overwritten once the buffer is full; `total` counts every event ever pushed
+/// and `overflowed` flips the first time a stored entry is discarded, mirroring
+/// a logic-analyzer capture log.
+struct EventRing {
+    buf: VecDeque<Transition>,
+    capacity: usize,
+    total: u64,
+    overflowed: bool,
+}
+
+impl EventRing {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buf: VecDeque::with_capacity(capacity),
+            capacity,
+            total: 0,
+            overflowed: false,
+        }
+    }
+
+    fn push(&mut self, t: Transition) {
+        if self.buf.len() == self.capacity {
+            self.buf.pop_front();
+            self.overflowed = true;
+        }
+        self.buf.push_back(t);
+        self.total += 1;
+    }
+
+    /// The most recent `n` transitions, oldest first.
+    fn recent(&self, n: usize) -> Vec<Transition> {
+        let skip = self.buf.len().saturating_sub(n);
+        self.buf.iter().skip(skip).copied().collect()
+    }
+
+    /// Every transition ever pushed, including ones since evicted.
+    fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Whether the ring has ever evicted an entry, i.e. a consumer that only
+    /// reads via [`RelayMonitor::recent`] has missed at least one transition.
+    fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+}
+
+/// A handle to a live `Subscribe` session. The background task keeps the
+/// connection open, diffs each pushed `Status` frame, and records transitions
+/// into a shared [`EventRing`] so an embedding program can replay recent
+/// history (e.g. after its own reconnect) via [`RelayMonitor::recent`].
+#[derive(Clone)]
+pub struct RelayMonitor {
+    ring: Arc<Mutex<EventRing>>,
+}
+
+impl RelayMonitor {
+    /// Subscribe to `addr` and start streaming transitions. The returned
+    /// receiver yields each transition as it is observed (for a live CLI view);
+    /// the same events are also retained in the ring buffer.
+    pub fn subscribe(addr: &str, mode: RelayMode, capacity: usize) -> (Self, mpsc::Receiver<Transition>) {
+        let ring = Arc::new(Mutex::new(EventRing::new(capacity)));
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(monitor_task(addr.to_string(), mode, ring.clone(), tx));
+        (Self { ring }, rx)
+    }
+
+    /// The last `n` buffered transitions, oldest first.
+    pub fn recent(&self, n: usize) -> Vec<Transition> {
+        self.ring.lock().unwrap().recent(n)
+    }
+
+    /// Every transition observed since subscribing, including ones since
+    /// evicted from the ring.
+    pub fn total(&self) -> u64 {
+        self.ring.lock().unwrap().total()
+    }
+
+    /// Whether the ring has ever evicted an entry. A consumer that only polls
+    /// [`RelayMonitor::recent`] can use this to tell it missed events, e.g.
+    /// across its own reconnect.
+    pub fn overflowed(&self) -> bool {
+        self.ring.lock().unwrap().overflowed()
+    }
+}
+
+/// Diff `cur` against `prev`, recording one [`Transition`] per changed relay
+/// into the ring and forwarding it to the live receiver.
+async fn record_transitions(
+    ring: &Arc<Mutex<EventRing>>,
+    events: &mpsc::Sender<Transition>,
+    prev: u8,
+    cur: u8,
+    num_relays: usize,
+) {
+    let changed = prev ^ cur;
+    let now = SystemTime::now();
+    for i in 0..num_relays {
+        if (changed >> i) & 1 == 1 {
+            let t = Transition {
+                at: now,
+                relay_id: i as u8,
+                on: (cur >> i) & 1 == 1,
+            };
+            ring.lock().unwrap().push(t);
+            // A dropped receiver just means nobody is watching live; the ring
+            // still has the event, so a send failure is not fatal. A full
+            // channel makes this wait until the receiver has room.
+            let _ = events.send(t).await;
+        }
+    }
+}
+
+/// The task behind a [`RelayMonitor`]. It (re)connects, sends `Subscribe`, then
+/// reads the device's `Status` push stream. A `Ping` is sent on a heartbeat
+/// interval; if the matching `Pong` has not arrived by the next tick the link
+/// is presumed dead and the subscription is re-established with backoff.
+async fn monitor_task(
+    addr: String,
+    mode: RelayMode,
+    ring: Arc<Mutex<EventRing>>,
+    events: mpsc::Sender<Transition>,
+) {
+    let heartbeat = Duration::from_secs(10);
+    let mut backoff = Duration::from_millis(100);
+    let max_backoff = Duration::from_secs(5);
+
+    loop {
+        let mut stream = match TcpStream::connect(&addr).await {
+            Ok(s) => {
+                backoff = Duration::from_millis(100);
+                s
+            }
+            Err(_) => {
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(max_backoff);
+                continue;
+            }
+        };
+
+        let sub = encode_request(&mode, Cmd::Subscribe, 0, 0);
+        if stream.write_all(&sub).await.is_err() {
+            continue;
+        }
+
+        let mut reader = FrameReader::new();
+        let mut buf = vec![0u8; 256];
+        let mut last_status: Option<u8> = None;
+        let mut awaiting_pong = false;
+        let mut ticker = tokio::time::interval(heartbeat);
+        ticker.tick().await; // Consume the immediate first tick.
+
+        'session: loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    // A still-outstanding pong means the previous heartbeat went
+                    // unanswered: treat the link as dead and reconnect.
+                    if awaiting_pong {
+                        break 'session;
+                    }
+                    let ping = encode_request(&mode, Cmd::Ping, 0, 0);
+                    if stream.write_all(&ping).await.is_err() {
+                        break 'session;
+                    }
+                    awaiting_pong = true;
+                }
+                read = stream.read(&mut buf) => {
+                    let n = match read {
+                        Ok(0) | Err(_) => break 'session,
+                        Ok(n) => n,
+                    };
+                    reader.feed(&buf[..n]);
+                    loop {
+                        let payload = match reader.next_frame(&mode) {
+                            Ok(Some(p)) => p,
+                            Ok(None) => break,
+                            Err(_) => break 'session,
+                        };
+                        let decoded = match decode_response(&mode, &payload) {
+                            Ok(d) => d,
+                            Err(_) => continue,
+                        };
+                        if decoded.len() < 2 {
+                            continue;
+                        }
+                        match RespType::try_from(decoded[1]) {
+                            Ok(RespType::Pong) => awaiting_pong = false,
+                            Ok(RespType::Status) if decoded.len() >= 4 => {
+                                let status = decoded[3];
+                                // First snapshot just seeds the baseline.
+                                if let Some(prev) = last_status {
+                                    record_transitions(&ring, &events, prev, status, 8).await;
+                                }
+                                last_status = Some(status);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A relay device that answered a discovery broadcast.
+struct Discovered {
+    addr: SocketAddr,
+    relays: u8,
+    version: u8,
+    id: u32,
+}
+
+/// Broadcast a discovery datagram on `port` and collect unicast replies for
+/// `window`. Modeled on the probe-and-listen pattern used by LAN server
+/// browsers: one socket sends `[MAGIC, DISCOVERY_PROBE]` to the subnet
+/// broadcast address, then gathers every reply that arrives before the window
+/// closes.
+async fn discover(port: u16, window: Duration) -> Result<Vec<Discovered>, Box<dyn std::error::Error + Send + Sync>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+    socket
+        .send_to(&[MAGIC, DISCOVERY_PROBE], (Ipv4Addr::BROADCAST, port))
+        .await?;
+
+    let mut found = Vec::new();
+    let mut buf = vec![0u8; 256];
+    let deadline = Instant::now() + window;
+    loop {
+        // Keep listening until the window elapses; a timeout just ends the scan.
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match tokio::time::timeout(remaining, socket.recv_from(&mut buf)).await {
+            Err(_) => break,
+            Ok(Ok((n, src))) => {
+                if let Some(dev) = parse_discovery_reply(&buf[..n], src) {
+                    if !found.iter().any(|d: &Discovered| d.addr == dev.addr) {
+                        found.push(dev);
+                    }
+                }
+            }
+            Ok(Err(e)) => return Err(e.into()),
+        }
+    }
+    Ok(found)
+}
+
+/// Parse a discovery reply of the form `[MAGIC, DISCOVERY_PROBE, relays,
+/// version, id0, id1, id2, id3]`, ignoring anything malformed or unrelated.
+fn parse_discovery_reply(data: &[u8], src: SocketAddr) -> Option<Discovered> {
+    if data.len() < 8 || data[0] != MAGIC || data[1] != DISCOVERY_PROBE {
+        return None;
+    }
+    let id = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    Some(Discovered {
+        addr: src,
+        relays: data[2],
+        version: data[3],
+        id,
+    })
+}
+
 fn print_relay_status(status: u8, num_relays: usize) {
     println!("Relay Status:");
     for i in 0..num_relays {
@@ -153,47 +724,183 @@ fn print_relay_status(status: u8, num_relays: usize) {
     }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = std::env::args().collect();
-    
+/// The parsed `--key` value (if any) alongside the remaining argument vector.
+type KeyArgs = (Option<[u8; 32]>, Vec<String>);
+
+/// Pull an optional `--key <hex>` flag out of the argument vector, falling
+/// back to the `RELAY_KEY` environment variable. Returns the parsed key (if
+/// any) and the argument vector with the flag removed.
+pub fn extract_key(mut args: Vec<String>) -> Result<KeyArgs, Box<dyn std::error::Error + Send + Sync>> {
+    let mut hex: Option<String> = None;
+    if let Some(pos) = args.iter().position(|a| a == "--key") {
+        let value = args.get(pos + 1).ok_or("--key requires a hex argument")?.clone();
+        args.drain(pos..pos + 2);
+        hex = Some(value);
+    } else if let Ok(value) = std::env::var("RELAY_KEY") {
+        hex = Some(value);
+    }
+
+    match hex {
+        Some(h) => Ok((Some(crypto::parse_key(&h)?), args)),
+        None => Ok((None, args)),
+    }
+}
+
+/// Pull a global `--json` flag out of the argument vector, returning whether it
+/// was present and the vector with the flag removed.
+fn extract_json_flag(mut args: Vec<String>) -> (bool, Vec<String>) {
+    if let Some(pos) = args.iter().position(|a| a == "--json") {
+        args.remove(pos);
+        (true, args)
+    } else {
+        (false, args)
+    }
+}
+
+/// Run a command in `--json` mode, printing exactly one serde_json object.
+/// Device and transport errors serialize to JSON and exit non-zero so the tool
+/// composes cleanly in shell pipelines.
+async fn run_json(client: &RelayClient, args: &[String]) {
+    use serde_json::json;
+
+    let result: Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> = match args[2].as_str() {
+        "ping" => {
+            let start = Instant::now();
+            client.ping().await.map(|_| {
+                let rtt_ms = start.elapsed().as_secs_f64() * 1000.0;
+                json!({ "pong": true, "rtt_ms": rtt_ms })
+            })
+        }
+        "status" => client.get_status().await.map(|status| {
+            let relays: Vec<serde_json::Value> = (0..8)
+                .map(|i| json!({ "id": i, "on": (status >> i) & 1 == 1 }))
+                .collect();
+            json!({ "relays": relays, "raw": format!("0x{:02X}", status) })
+        }),
+        "set" if args.len() >= 5 => match (args[3].parse::<u8>(), args[4].parse::<u8>()) {
+            (Ok(relay_id), Ok(state)) => {
+                client.set_relay(relay_id, state != 0).await.map(|_| json!({ "ok": true }))
+            }
+            _ => Err("invalid set arguments".into()),
+        },
+        "toggle" if args.len() >= 4 => match args[3].parse::<u8>() {
+            Ok(relay_id) => client.toggle_relay(relay_id).await.map(|_| json!({ "ok": true })),
+            Err(_) => Err("invalid relay id".into()),
+        },
+        "all" if args.len() >= 4 => match u8::from_str_radix(&args[3], 16) {
+            Ok(bitmask) => client.set_all(bitmask).await.map(|_| json!({ "ok": true })),
+            Err(_) => Err("invalid bitmask".into()),
+        },
+        _ => Err("invalid command".into()),
+    };
+
+    match result {
+        Ok(value) => println!("{}", value),
+        Err(e) => {
+            let msg = e.to_string();
+            // Surface the device's error code when the failure came from the board.
+            let value = match msg.strip_prefix("Device error: ") {
+                Some(code) => json!({ "ok": false, "error_code": code }),
+                None => json!({ "ok": false, "error": msg }),
+            };
+            println!("{}", value);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (key, args) = extract_key(std::env::args().collect())?;
+    let (json_mode, args) = extract_json_flag(args);
+
+    // `discover` is not tied to a specific host, so it is dispatched before the
+    // usual `<host:port> <command>` parsing.
+    if args.get(1).map(|s| s.as_str()) == Some("discover") {
+        let port = match args.get(2) {
+            Some(p) => p.parse()?,
+            None => DEFAULT_PORT,
+        };
+        let devices = discover(port, Duration::from_secs(2)).await?;
+        if devices.is_empty() {
+            println!("No relay devices found on port {}.", port);
+        } else {
+            println!("Discovered {} device(s):", devices.len());
+            for dev in devices {
+                println!(
+                    "  {} (id 0x{:08X}, {} relays, fw v{})",
+                    dev.addr, dev.id, dev.relays, dev.version
+                );
+            }
+        }
+        return Ok(());
+    }
+
     if args.len() < 3 {
-        eprintln!("Usage: {} <host:port> <command> [args...]", args[0]);
+        eprintln!("Usage: {} [--key <hex>] [--json] <host:port> <command> [args...]", args[0]);
+        eprintln!("       {} discover [port]", args[0]);
         eprintln!("Commands:");
         eprintln!("  ping");
         eprintln!("  status");
         eprintln!("  set <relay_id> <0|1>");
         eprintln!("  toggle <relay_id>");
         eprintln!("  all <bitmask>");
+        eprintln!("  monitor");
         return Ok(());
     }
-    
-    let client = RelayClient::new(&args[1]);
-    
+
+    let client = match key {
+        Some(key) => RelayClient::with_key(&args[1], key),
+        None => RelayClient::new(&args[1]),
+    };
+
+    if json_mode {
+        run_json(&client, &args).await;
+        return Ok(());
+    }
+
     match args[2].as_str() {
         "ping" => {
-            client.ping()?;
+            client.ping().await?;
             println!("Pong!");
         }
         "status" => {
-            let status = client.get_status()?;
+            let status = client.get_status().await?;
             print_relay_status(status, 8);
         }
         "set" if args.len() >= 5 => {
             let relay_id: u8 = args[3].parse()?;
             let state: u8 = args[4].parse()?;
-            client.set_relay(relay_id, state != 0)?;
+            client.set_relay(relay_id, state != 0).await?;
             println!("OK");
         }
         "toggle" if args.len() >= 4 => {
             let relay_id: u8 = args[3].parse()?;
-            client.toggle_relay(relay_id)?;
+            client.toggle_relay(relay_id).await?;
             println!("OK");
         }
         "all" if args.len() >= 4 => {
             let bitmask: u8 = u8::from_str_radix(&args[3], 16)?;
-            client.set_all(bitmask)?;
+            client.set_all(bitmask).await?;
             println!("OK");
         }
+        "monitor" => {
+            let mode = match key {
+                Some(key) => RelayMode::Encrypted(key),
+                None => RelayMode::Plain,
+            };
+            let (_monitor, mut events) = RelayMonitor::subscribe(&args[1], mode, 256);
+            println!("Monitoring {} (Ctrl-C to stop)...", args[1]);
+            while let Some(ev) = events.recv().await {
+                let ms = ev.at.duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+                println!(
+                    "[{}] Relay {} -> {}",
+                    ms,
+                    ev.relay_id,
+                    if ev.on { "ON" } else { "OFF" }
+                );
+            }
+        }
         _ => {
             eprintln!("Invalid command");
         }